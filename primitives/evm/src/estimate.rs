@@ -0,0 +1,229 @@
+// SPDX-License-Identifier: Apache-2.0
+// This file is part of Frontier.
+//
+// Copyright (c) 2020-2022 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{ExecutionInfo, ExitReason, ResourceDimension};
+
+/// Intrinsic gas cost: the lower bound of the `eth_estimateGas` binary search.
+pub const ESTIMATE_GAS_LOWEST_LIMIT: u64 = 21_000;
+
+/// Default convergence threshold for the `eth_estimateGas` binary search: once the search
+/// window narrows to this many gas units or fewer, the upper bound is returned as the estimate.
+pub const ESTIMATE_GAS_RESOLUTION: u64 = 10;
+
+/// Result of [`estimate_gas`]: the minimal gas limit at which execution succeeds, together with
+/// the `ExecutionInfo` obtained at that gas limit so callers can inspect logs and revert data.
+pub struct GasEstimate<T> {
+	pub gas_limit: u64,
+	pub exec_info: ExecutionInfo<T>,
+}
+
+fn is_executed_successfully<T>(info: &ExecutionInfo<T>) -> bool {
+	matches!(info.exit_reason, ExitReason::Succeed(_))
+}
+
+/// `true` if `info`'s ref-time is under its limit but its proof-size usage has hit its limit.
+fn proof_size_is_saturated<T>(info: &ExecutionInfo<T>) -> bool {
+	let Some(weight_info) = info.weight_info.as_ref() else {
+		return false;
+	};
+	let ref_time_ok = match (
+		weight_info.usage(ResourceDimension::RefTime),
+		weight_info.limit(ResourceDimension::RefTime),
+	) {
+		(Some(usage), Some(limit)) => usage < limit,
+		_ => true,
+	};
+	let proof_size_saturated = match (
+		weight_info.usage(ResourceDimension::ProofSize),
+		weight_info.limit(ResourceDimension::ProofSize),
+	) {
+		(Some(usage), Some(limit)) => usage >= limit,
+		_ => false,
+	};
+	ref_time_ok && proof_size_saturated
+}
+
+/// Binary-search `[ESTIMATE_GAS_LOWEST_LIMIT, gas_cap]` for the minimal gas limit at which `run`
+/// succeeds, for `eth_estimateGas`-style callers. Gives up immediately if `gas_cap` itself fails.
+///
+/// If the result saturates its proof-size limit while ref-time has headroom, continues the
+/// search above that gas limit (up to `gas_cap`) for one that also clears proof-size, so
+/// PoV-metered chains aren't under-estimated.
+pub fn estimate_gas<T, E>(
+	mut run: impl FnMut(u64) -> Result<ExecutionInfo<T>, E>,
+	gas_cap: u64,
+	resolution: u64,
+) -> Result<GasEstimate<T>, E> {
+	assert!(
+		gas_cap >= ESTIMATE_GAS_LOWEST_LIMIT,
+		"gas_cap must be at least the intrinsic gas cost ({ESTIMATE_GAS_LOWEST_LIMIT})"
+	);
+
+	let mut lowest = ESTIMATE_GAS_LOWEST_LIMIT;
+	let mut highest = gas_cap;
+
+	let mut info = run(highest)?;
+	if !is_executed_successfully(&info) {
+		return Ok(GasEstimate {
+			gas_limit: highest,
+			exec_info: info,
+		});
+	}
+
+	while highest - lowest > resolution {
+		let mid = lowest + (highest - lowest) / 2;
+		let mid_info = run(mid)?;
+		if is_executed_successfully(&mid_info) {
+			highest = mid;
+			info = mid_info;
+		} else {
+			lowest = mid;
+		}
+	}
+
+	if proof_size_is_saturated(&info) && highest < gas_cap {
+		let cap_info = run(gas_cap)?;
+		if is_executed_successfully(&cap_info) && !proof_size_is_saturated(&cap_info) {
+			let mut proof_lowest = highest;
+			let mut proof_highest = gas_cap;
+			let mut proof_info = cap_info;
+
+			while proof_highest - proof_lowest > resolution {
+				let mid = proof_lowest + (proof_highest - proof_lowest) / 2;
+				let mid_info = run(mid)?;
+				if is_executed_successfully(&mid_info) && !proof_size_is_saturated(&mid_info) {
+					proof_highest = mid;
+					proof_info = mid_info;
+				} else {
+					proof_lowest = mid;
+				}
+			}
+
+			highest = proof_highest;
+			info = proof_info;
+		}
+	}
+
+	Ok(GasEstimate {
+		gas_limit: highest,
+		exec_info: info,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{ExitError, ResourceVector};
+	use evm::ExitSucceed;
+	use frame_support::weights::Weight;
+	use sp_core::U256;
+	use sp_std::vec::Vec;
+
+	fn succeed(gas: u64, weight_info: Option<ResourceVector>) -> ExecutionInfo<()> {
+		ExecutionInfo {
+			exit_reason: ExitReason::Succeed(ExitSucceed::Returned),
+			value: (),
+			used_gas: U256::from(gas),
+			weight_info,
+			logs: Vec::new(),
+			effective_gas_price: None,
+		}
+	}
+
+	fn out_of_gas(gas: u64) -> ExecutionInfo<()> {
+		ExecutionInfo {
+			exit_reason: ExitReason::Error(ExitError::OutOfGas),
+			value: (),
+			used_gas: U256::from(gas),
+			weight_info: None,
+			logs: Vec::new(),
+			effective_gas_price: None,
+		}
+	}
+
+	#[test]
+	fn succeeds_immediately_at_cap() {
+		let estimate = estimate_gas(
+			|gas| Ok::<_, ()>(succeed(gas, None)),
+			ESTIMATE_GAS_LOWEST_LIMIT,
+			ESTIMATE_GAS_RESOLUTION,
+		)
+		.unwrap();
+		assert_eq!(estimate.gas_limit, ESTIMATE_GAS_LOWEST_LIMIT);
+	}
+
+	#[test]
+	fn gives_up_when_cap_fails() {
+		let estimate = estimate_gas(|gas| Ok::<_, ()>(out_of_gas(gas)), 100_000, ESTIMATE_GAS_RESOLUTION).unwrap();
+		assert_eq!(estimate.gas_limit, 100_000);
+		assert!(!matches!(estimate.exec_info.exit_reason, ExitReason::Succeed(_)));
+	}
+
+	#[test]
+	fn converges_to_minimal_gas() {
+		const THRESHOLD: u64 = 30_000;
+		let estimate = estimate_gas(
+			|gas| {
+				Ok::<_, ()>(if gas >= THRESHOLD {
+					succeed(gas, None)
+				} else {
+					out_of_gas(gas)
+				})
+			},
+			100_000,
+			1,
+		)
+		.unwrap();
+		assert!(estimate.gas_limit >= THRESHOLD && estimate.gas_limit <= THRESHOLD + 1);
+	}
+
+	#[test]
+	fn widens_estimate_when_proof_size_is_saturated() {
+		// proof_size_limit scales 1:1 with the candidate gas limit, while ref-time is never
+		// binding; a fixed 50_000-byte proof is recorded regardless of gas.
+		const PROOF_SIZE_USAGE: u64 = 50_000;
+		let run = |gas: u64| {
+			let mut weight_info = ResourceVector::new_from_weight_limit(Some(Weight::from_parts(1_000_000_000, gas)))
+				.unwrap()
+				.unwrap();
+			let ref_time_ok = weight_info.try_record(ResourceDimension::RefTime, 1_000).is_ok();
+			let proof_size_ok = weight_info
+				.try_record(ResourceDimension::ProofSize, PROOF_SIZE_USAGE)
+				.is_ok();
+			Ok::<_, ()>(if ref_time_ok && proof_size_ok {
+				succeed(gas, Some(weight_info))
+			} else {
+				out_of_gas(gas)
+			})
+		};
+
+		let estimate = estimate_gas(run, 100_000, 1).unwrap();
+		// A plain exit-reason-only search would stop at 50_000 (the smallest gas limit at which
+		// the proof fits at all), leaving zero headroom. The widening search must move past it.
+		assert!(estimate.gas_limit > PROOF_SIZE_USAGE);
+		let weight_info = estimate.exec_info.weight_info.unwrap();
+		assert!(
+			weight_info.usage(ResourceDimension::ProofSize) < weight_info.limit(ResourceDimension::ProofSize)
+		);
+	}
+
+	#[test]
+	#[should_panic(expected = "gas_cap must be at least the intrinsic gas cost")]
+	fn rejects_cap_below_intrinsic_gas() {
+		let _ = estimate_gas(|gas| Ok::<_, ()>(succeed(gas, None)), 1_000, 1);
+	}
+}
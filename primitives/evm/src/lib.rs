@@ -18,6 +18,7 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 #![deny(unused_crate_dependencies)]
 
+mod estimate;
 mod precompile;
 mod validation;
 
@@ -35,6 +36,7 @@ pub use evm::{
 };
 
 pub use self::{
+	estimate::{estimate_gas, GasEstimate, ESTIMATE_GAS_LOWEST_LIMIT, ESTIMATE_GAS_RESOLUTION},
 	precompile::{
 		Context, ExitError, ExitRevert, ExitSucceed, LinearCostPrecompile, Precompile, IsPrecompileResult,
 		PrecompileFailure, PrecompileHandle, PrecompileOutput, PrecompileResult, PrecompileSet,
@@ -54,83 +56,142 @@ pub struct Vicinity {
 	pub gas_price: U256,
 	/// Origin of the transaction.
 	pub origin: H160,
+	/// Account that pays the transaction's fees, if different from `origin`.
+	pub fee_payer: Option<H160>,
 }
 
+impl Vicinity {
+	/// The account that pays this transaction's fees.
+	pub fn fee_payer(&self) -> H160 {
+		self.fee_payer.unwrap_or(self.origin)
+	}
+}
+
+/// Number of resource dimensions tracked by a [`ResourceVector`].
+const RESOURCE_DIMENSIONS: usize = 3;
+
+/// A single metered resource dimension.
 #[derive(Clone, Copy, Eq, PartialEq, Encode, Decode)]
 #[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
-pub struct WeightInfo {
-	pub ref_time_limit: Option<u64>,
-	pub proof_size_limit: Option<u64>,
-	pub ref_time_usage: Option<u64>,
-	pub proof_size_usage: Option<u64>,
+pub enum ResourceDimension {
+	/// Time spent executing on the reference machine.
+	RefTime,
+	/// Size of the storage proof generated by the execution.
+	ProofSize,
+	/// L1 data-availability gas charged for calldata/blobs (a.k.a. storage bytes).
+	L1DataGas,
 }
 
-impl WeightInfo {
+impl ResourceDimension {
+	const ALL: [ResourceDimension; RESOURCE_DIMENSIONS] = [
+		ResourceDimension::RefTime,
+		ResourceDimension::ProofSize,
+		ResourceDimension::L1DataGas,
+	];
+
+	fn index(self) -> usize {
+		match self {
+			ResourceDimension::RefTime => 0,
+			ResourceDimension::ProofSize => 1,
+			ResourceDimension::L1DataGas => 2,
+		}
+	}
+}
+
+/// A small fixed-size vector of independently metered resource dimensions. A `None` limit means
+/// the dimension is unmetered and can never fail.
+#[derive(Clone, Copy, Eq, PartialEq, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+pub struct ResourceVector {
+	limits: [Option<u64>; RESOURCE_DIMENSIONS],
+	usage: [Option<u64>; RESOURCE_DIMENSIONS],
+}
+
+/// Alias kept for the `ExecutionInfo::weight_info` field name.
+pub type WeightInfo = ResourceVector;
+
+impl ResourceVector {
+	/// A vector with every dimension unmetered (`None` limit and usage).
+	fn unmetered() -> Self {
+		ResourceVector {
+			limits: [None; RESOURCE_DIMENSIONS],
+			usage: [None; RESOURCE_DIMENSIONS],
+		}
+	}
+
 	pub fn new_from_weight_limit(weight_limit: Option<Weight>) -> Result<Option<Self>, &'static str> {
 		Ok(match weight_limit {
 			None => None,
 			Some(weight_limit) if weight_limit.ref_time() > 0
-				&& weight_limit.proof_size() > 0 => Some(WeightInfo {
-					ref_time_limit: Some(weight_limit.ref_time()),
-					proof_size_limit: Some(weight_limit.proof_size()),
-					ref_time_usage: Some(0u64),
-					proof_size_usage: Some(0u64),
-				}),
-			Some(weight_limit) if weight_limit.ref_time() > 0 => Some(WeightInfo {
-					ref_time_limit: Some(weight_limit.ref_time()),
-					proof_size_limit: None,
-					ref_time_usage: Some(0u64),
-					proof_size_usage: None,
-				}),
-			Some(weight_limit) if weight_limit.proof_size() > 0 => Some(WeightInfo {
-					ref_time_limit: None,
-					proof_size_limit: Some(weight_limit.proof_size()),
-					ref_time_usage: None,
-					proof_size_usage: Some(0u64),
-				}),
+				&& weight_limit.proof_size() > 0 => {
+					let mut vector = Self::unmetered();
+					vector.set_limit(ResourceDimension::RefTime, weight_limit.ref_time());
+					vector.set_limit(ResourceDimension::ProofSize, weight_limit.proof_size());
+					Some(vector)
+				}
+			Some(weight_limit) if weight_limit.ref_time() > 0 => {
+				let mut vector = Self::unmetered();
+				vector.set_limit(ResourceDimension::RefTime, weight_limit.ref_time());
+				Some(vector)
+			}
+			Some(weight_limit) if weight_limit.proof_size() > 0 => {
+				let mut vector = Self::unmetered();
+				vector.set_limit(ResourceDimension::ProofSize, weight_limit.proof_size());
+				Some(vector)
+			}
 			_ => return Err("must provide Some valid weight limit or None")
 		})
 	}
-	fn try_consume(&self, cost: u64, limit: u64, usage: u64) -> Result<u64, ExitError> {
-		let usage = usage
-			.checked_add(cost)
-			.ok_or(ExitError::OutOfGas)?;
-		if usage > limit {
-			return Err(ExitError::OutOfGas);
-		}
-		Ok(usage)
+
+	fn set_limit(&mut self, dimension: ResourceDimension, limit: u64) {
+		let idx = dimension.index();
+		self.limits[idx] = Some(limit);
+		self.usage[idx] = Some(0);
 	}
-	pub fn try_record_ref_time_or_fail(&mut self, cost: u64) -> Result<(), ExitError>  {
-		if let (Some(ref_time_usage), Some(ref_time_limit)) = (self.ref_time_usage, self.ref_time_limit) {
-			let ref_time_usage = self.try_consume(cost, ref_time_limit, ref_time_usage)?;
-			if ref_time_usage > ref_time_limit {
-				return Err(ExitError::OutOfGas);
-			}
-			self.ref_time_usage = Some(ref_time_usage);
-		}
-		Ok(())
+
+	/// Limit configured for `dimension`, or `None` if it is unmetered.
+	pub fn limit(&self, dimension: ResourceDimension) -> Option<u64> {
+		self.limits[dimension.index()]
 	}
-	pub fn try_record_proof_size_or_fail(&mut self, cost: u64) -> Result<(), ExitError> {
-		if let (Some(proof_size_usage), Some(proof_size_limit)) = (self.proof_size_usage, self.proof_size_limit) {
-			let proof_size_usage = self.try_consume(cost, proof_size_limit, proof_size_usage)?;
-			if proof_size_usage > proof_size_limit {
+
+	/// Usage recorded so far for `dimension`, or `None` if it is unmetered.
+	pub fn usage(&self, dimension: ResourceDimension) -> Option<u64> {
+		self.usage[dimension.index()]
+	}
+
+	/// Record `cost` against `dimension`, failing if doing so would exceed its limit.
+	pub fn try_record(&mut self, dimension: ResourceDimension, cost: u64) -> Result<(), ExitError> {
+		let idx = dimension.index();
+		if let (Some(usage), Some(limit)) = (self.usage[idx], self.limits[idx]) {
+			let usage = usage.checked_add(cost).ok_or(ExitError::OutOfGas)?;
+			if usage > limit {
 				return Err(ExitError::OutOfGas);
 			}
-			self.proof_size_usage = Some(proof_size_usage);
+			self.usage[idx] = Some(usage);
 		}
 		Ok(())
 	}
-	pub fn refund_proof_size(&mut self, amount: u64) {
-		if let Some(proof_size_usage) = self.proof_size_usage {
-			let proof_size_usage = proof_size_usage.saturating_sub(amount);
-			self.proof_size_usage = Some(proof_size_usage);
+
+	/// Refund `amount` of previously recorded usage for `dimension`, saturating at zero.
+	pub fn refund(&mut self, dimension: ResourceDimension, amount: u64) {
+		let idx = dimension.index();
+		if let Some(usage) = self.usage[idx] {
+			self.usage[idx] = Some(usage.saturating_sub(amount));
 		}
 	}
-	pub fn refund_ref_time(&mut self, amount: u64) {
-		if let Some(ref_time_usage) = self.ref_time_usage {
-			let ref_time_usage = ref_time_usage.saturating_sub(amount);
-			self.ref_time_usage = Some(ref_time_usage);
-		}
+
+	/// Collapse the vector into a single gas figure: the max of `usage[i] * price[i]`.
+	pub fn to_discounted_gas(&self, price_per_dimension: &[u64]) -> u64 {
+		ResourceDimension::ALL
+			.iter()
+			.map(|dimension| {
+				let idx = dimension.index();
+				let usage = self.usage[idx].unwrap_or(0);
+				let price = price_per_dimension.get(idx).copied().unwrap_or(0);
+				usage.saturating_mul(price)
+			})
+			.max()
+			.unwrap_or(0)
 	}
 }
 
@@ -142,6 +203,9 @@ pub struct ExecutionInfo<T> {
 	pub used_gas: U256,
 	pub weight_info: Option<WeightInfo>,
 	pub logs: Vec<Log>,
+	/// The EIP-1559 effective gas price charged for this execution. `None` for legacy
+	/// transactions.
+	pub effective_gas_price: Option<U256>,
 }
 
 pub type CallInfo = ExecutionInfo<Vec<u8>>;
@@ -172,6 +236,16 @@ pub struct GenesisAccount {
 pub trait FeeCalculator {
 	/// Return the minimal required gas price.
 	fn min_gas_price() -> (U256, Weight);
+
+	/// The EIP-1559 effective gas price: `min(max_fee_per_gas, base_fee + max_priority_fee_per_gas)`.
+	fn effective_gas_price(
+		base_fee: U256,
+		max_fee_per_gas: U256,
+		max_priority_fee_per_gas: Option<U256>,
+	) -> U256 {
+		let priority_fee = max_priority_fee_per_gas.unwrap_or_default();
+		sp_std::cmp::min(max_fee_per_gas, base_fee.saturating_add(priority_fee))
+	}
 }
 
 impl FeeCalculator for () {
@@ -223,4 +297,67 @@ mod tests {
 			1
 		);
 	}
+
+	#[test]
+	fn effective_gas_price_is_min_of_max_fee_and_base_plus_priority() {
+		// base_fee + priority_fee is cheaper than max_fee_per_gas.
+		assert_eq!(
+			<() as FeeCalculator>::effective_gas_price(U256::from(10u64), U256::from(100u64), Some(U256::from(5u64))),
+			U256::from(15u64)
+		);
+		// max_fee_per_gas is the binding cap.
+		assert_eq!(
+			<() as FeeCalculator>::effective_gas_price(U256::from(10u64), U256::from(12u64), Some(U256::from(5u64))),
+			U256::from(12u64)
+		);
+		// Missing priority fee defaults to zero.
+		assert_eq!(
+			<() as FeeCalculator>::effective_gas_price(U256::from(10u64), U256::from(100u64), None),
+			U256::from(10u64)
+		);
+	}
+
+	#[test]
+	fn try_record_rejects_usage_above_limit() {
+		let mut vector = ResourceVector::new_from_weight_limit(Some(Weight::from_parts(100, 50)))
+			.unwrap()
+			.unwrap();
+		assert!(vector.try_record(ResourceDimension::RefTime, 100).is_ok());
+		assert_eq!(vector.usage(ResourceDimension::RefTime), Some(100));
+		assert_eq!(
+			vector.try_record(ResourceDimension::RefTime, 1),
+			Err(ExitError::OutOfGas)
+		);
+	}
+
+	#[test]
+	fn unmetered_dimension_never_fails() {
+		let mut vector = ResourceVector::new_from_weight_limit(Some(Weight::from_parts(100, 0)))
+			.unwrap()
+			.unwrap();
+		assert_eq!(vector.limit(ResourceDimension::ProofSize), None);
+		assert!(vector.try_record(ResourceDimension::ProofSize, u64::MAX).is_ok());
+		assert_eq!(vector.usage(ResourceDimension::ProofSize), None);
+	}
+
+	#[test]
+	fn refund_saturates_at_zero() {
+		let mut vector = ResourceVector::new_from_weight_limit(Some(Weight::from_parts(100, 100)))
+			.unwrap()
+			.unwrap();
+		vector.try_record(ResourceDimension::RefTime, 10).unwrap();
+		vector.refund(ResourceDimension::RefTime, 100);
+		assert_eq!(vector.usage(ResourceDimension::RefTime), Some(0));
+	}
+
+	#[test]
+	fn to_discounted_gas_takes_the_max_dimension_not_the_sum() {
+		let mut vector = ResourceVector::new_from_weight_limit(Some(Weight::from_parts(1_000, 1_000)))
+			.unwrap()
+			.unwrap();
+		vector.try_record(ResourceDimension::RefTime, 10).unwrap();
+		vector.try_record(ResourceDimension::ProofSize, 100).unwrap();
+		// ref_time: 10 * 1 = 10, proof_size: 100 * 5 = 500; the max (500) should win, not 10 + 500.
+		assert_eq!(vector.to_discounted_gas(&[1, 5, 0]), 500);
+	}
 }
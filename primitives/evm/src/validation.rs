@@ -0,0 +1,404 @@
+// SPDX-License-Identifier: Apache-2.0
+// This file is part of Frontier.
+//
+// Copyright (c) 2020-2022 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use scale_codec::{Decode, Encode};
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_core::{H160, H256, U256};
+use sp_std::vec::Vec;
+
+/// The EIP-7702 delegation designator prefix. An account whose code starts with this prefix is
+/// still considered an EOA for the purposes of EIP-3607, since the code is not "deployed" in the
+/// usual sense but merely points at a delegate.
+pub const EIP7702_DELEGATION_DESIGNATOR_PREFIX: [u8; 3] = [0xef, 0x01, 0x00];
+/// Length in bytes of the EIP-7702 delegation designator (`0xef0100` followed by a 20-byte
+/// address).
+pub const EIP7702_DELEGATION_DESIGNATOR_LEN: usize = 23;
+
+/// Returns `true` if `code` is a delegation designator installed by EIP-7702, i.e. it is allowed
+/// to originate transactions despite carrying code.
+fn is_eip7702_delegation_designator(code: &[u8]) -> bool {
+	code.len() == EIP7702_DELEGATION_DESIGNATOR_LEN
+		&& code.starts_with(&EIP7702_DELEGATION_DESIGNATOR_PREFIX)
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub enum InvalidEvmTransactionError {
+	/// Provided gas limit is lower than the intrinsic gas cost of the transaction.
+	GasLimitTooLow,
+	/// Provided gas limit is higher than the remaining block gas limit.
+	GasLimitTooHigh,
+	/// Provided gas price is lower than the minimum gas price.
+	GasPriceTooLow,
+	/// Provided priority fee is higher than the max fee.
+	PriorityFeeTooHigh,
+	/// Sender does not have enough balance to cover the transaction's cost.
+	BalanceTooLow,
+	/// Transaction nonce is too low.
+	TxNonceTooLow,
+	/// Transaction nonce is too high.
+	TxNonceTooHigh,
+	/// Gas price and/or priority fee inputs are invalid/inconsistent.
+	InvalidPaymentInput,
+	/// `max_fee_per_gas` is lower than the block's base fee.
+	GasPriceLessThanBaseFee,
+	/// Chain id does not match the configured chain id.
+	InvalidChainId,
+	/// Transaction originates from an account that carries deployed bytecode, which EIP-3607
+	/// disallows (accounts with code other than an EIP-7702 delegation designator may not send
+	/// transactions).
+	SenderHasDeployedCode,
+	/// The sponsoring fee payer of a meta-transaction does not have enough balance to cover the
+	/// transaction's cost. Distinct from `BalanceTooLow`, which is reported when `origin` pays
+	/// its own fees.
+	FeePayerBalanceTooLow,
+}
+
+/// Input data for EVM transaction validation, independent of the configuration the caller wants
+/// to validate against.
+#[derive(Debug, Clone)]
+pub struct CheckEvmTransactionInput {
+	pub chain_id: Option<u64>,
+	pub to: Option<H160>,
+	pub input: Vec<u8>,
+	pub nonce: U256,
+	pub gas_limit: U256,
+	pub gas_price: Option<U256>,
+	pub max_fee_per_gas: Option<U256>,
+	pub max_priority_fee_per_gas: Option<U256>,
+	pub value: U256,
+	pub access_list: Vec<(H160, Vec<H256>)>,
+	/// Code currently deployed at the sender's address, if any. Used to enforce EIP-3607.
+	pub sender_code: Option<Vec<u8>>,
+	/// The account sponsoring this transaction's fees, if different from the signer. When set,
+	/// `with_balance_for`/`validate_in_block_for` check their `fee_payer_balance` argument
+	/// rather than the signer's balance, and report `FeePayerBalanceTooLow` instead of
+	/// `BalanceTooLow` on insufficient or missing funds.
+	pub fee_payer: Option<H160>,
+}
+
+/// Configuration for EVM transaction validation.
+#[derive(Debug, Clone)]
+pub struct CheckEvmTransactionConfig<'config> {
+	pub evm_config: &'config evm::Config,
+	pub block_gas_limit: U256,
+	pub base_fee: U256,
+	pub chain_id: u64,
+	pub is_transactional: bool,
+}
+
+/// Checks that a transaction is valid, either to be included in a block or to be accepted for the
+/// transaction pool.
+#[derive(Debug, Clone)]
+pub struct CheckEvmTransaction<'config> {
+	pub config: CheckEvmTransactionConfig<'config>,
+	pub transaction: CheckEvmTransactionInput,
+}
+
+impl<'config> CheckEvmTransaction<'config> {
+	pub fn new(config: CheckEvmTransactionConfig<'config>, transaction: CheckEvmTransactionInput) -> Self {
+		Self { config, transaction }
+	}
+
+	/// Validate the transaction so that it can be included in a block.
+	///
+	/// `who_nonce` is the signer's nonce, used for the nonce check. `origin_balance` is the
+	/// signer's balance, used for the balance check when there is no `fee_payer`. `fee_payer_balance` must be `Some` whenever
+	/// `transaction.fee_payer` is set: the struct itself picks which of the two is checked
+	/// against the transaction's cost, rather than trusting the caller to have already resolved
+	/// the right account.
+	pub fn validate_in_block_for(
+		self,
+		who_nonce: &U256,
+		origin_balance: &U256,
+		fee_payer_balance: Option<&U256>,
+	) -> Result<Self, InvalidEvmTransactionError> {
+		if self.transaction.nonce < *who_nonce {
+			return Err(InvalidEvmTransactionError::TxNonceTooLow);
+		} else if self.config.is_transactional && self.transaction.nonce > *who_nonce {
+			return Err(InvalidEvmTransactionError::TxNonceTooHigh);
+		}
+		self.with_balance_for(origin_balance, fee_payer_balance)
+	}
+
+	/// Validate the transaction so that it can be accepted by the transaction pool and by the
+	/// runtime, regardless of block inclusion.
+	pub fn validate_common(self) -> Result<Self, InvalidEvmTransactionError> {
+		if self.config.is_transactional {
+			if self.transaction.gas_limit < self.config.evm_config.gas_transaction_call.into() {
+				return Err(InvalidEvmTransactionError::GasLimitTooLow);
+			}
+			if self.transaction.gas_limit > self.config.block_gas_limit {
+				return Err(InvalidEvmTransactionError::GasLimitTooHigh);
+			}
+		}
+		if let Some(chain_id) = self.transaction.chain_id {
+			if chain_id != self.config.chain_id {
+				return Err(InvalidEvmTransactionError::InvalidChainId);
+			}
+		}
+		if let Some(sender_code) = self.transaction.sender_code.as_deref() {
+			if !sender_code.is_empty() && !is_eip7702_delegation_designator(sender_code) {
+				return Err(InvalidEvmTransactionError::SenderHasDeployedCode);
+			}
+		}
+		if let Some(max_fee_per_gas) = self.transaction.max_fee_per_gas {
+			if max_fee_per_gas < self.config.base_fee {
+				return Err(InvalidEvmTransactionError::GasPriceLessThanBaseFee);
+			}
+			if let Some(max_priority_fee_per_gas) = self.transaction.max_priority_fee_per_gas {
+				if max_fee_per_gas < max_priority_fee_per_gas {
+					return Err(InvalidEvmTransactionError::PriorityFeeTooHigh);
+				}
+			}
+		}
+		Ok(self)
+	}
+
+	fn total_payment(&self) -> Result<U256, InvalidEvmTransactionError> {
+		let effective_gas_price = self
+			.transaction
+			.gas_price
+			.or(self.transaction.max_fee_per_gas)
+			.ok_or(InvalidEvmTransactionError::InvalidPaymentInput)?;
+		Ok(effective_gas_price
+			.saturating_mul(self.transaction.gas_limit)
+			.saturating_add(self.transaction.value))
+	}
+
+	/// Validate that the correct account covers the worst-case cost of the transaction (gas
+	/// limit at the provided price, plus value). When `transaction.fee_payer` is set,
+	/// `fee_payer_balance` is checked and must be provided; otherwise `origin_balance` is
+	/// checked. The struct makes this choice itself so a caller cannot silently check the wrong
+	/// account's balance.
+	pub fn with_balance_for(
+		self,
+		origin_balance: &U256,
+		fee_payer_balance: Option<&U256>,
+	) -> Result<Self, InvalidEvmTransactionError> {
+		if self.config.is_transactional {
+			let total_payment = self.total_payment()?;
+			let (balance, error) = match self.transaction.fee_payer {
+				Some(_) => (
+					*fee_payer_balance.ok_or(InvalidEvmTransactionError::FeePayerBalanceTooLow)?,
+					InvalidEvmTransactionError::FeePayerBalanceTooLow,
+				),
+				None => (*origin_balance, InvalidEvmTransactionError::BalanceTooLow),
+			};
+			if balance < total_payment {
+				return Err(error);
+			}
+		}
+		Ok(self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn evm_config() -> evm::Config {
+		evm::Config::istanbul()
+	}
+
+	fn base_input() -> CheckEvmTransactionInput {
+		CheckEvmTransactionInput {
+			chain_id: Some(1),
+			to: Some(H160::default()),
+			input: Vec::new(),
+			nonce: U256::zero(),
+			gas_limit: U256::from(100_000u64),
+			gas_price: Some(U256::from(1u64)),
+			max_fee_per_gas: None,
+			max_priority_fee_per_gas: None,
+			value: U256::zero(),
+			access_list: Vec::new(),
+			sender_code: None,
+			fee_payer: None,
+		}
+	}
+
+	fn check_with_base_fee<'config>(
+		evm_config: &'config evm::Config,
+		transaction: CheckEvmTransactionInput,
+		base_fee: U256,
+	) -> CheckEvmTransaction<'config> {
+		CheckEvmTransaction::new(
+			CheckEvmTransactionConfig {
+				evm_config,
+				block_gas_limit: U256::from(15_000_000u64),
+				base_fee,
+				chain_id: 1,
+				is_transactional: true,
+			},
+			transaction,
+		)
+	}
+
+	fn check<'config>(evm_config: &'config evm::Config, transaction: CheckEvmTransactionInput) -> CheckEvmTransaction<'config> {
+		check_with_base_fee(evm_config, transaction, U256::zero())
+	}
+
+	#[test]
+	fn allows_sender_with_no_code() {
+		let config = evm_config();
+		assert!(check(&config, base_input()).validate_common().is_ok());
+
+		let mut tx = base_input();
+		tx.sender_code = Some(Vec::new());
+		assert!(check(&config, tx).validate_common().is_ok());
+	}
+
+	#[test]
+	fn rejects_sender_with_deployed_code() {
+		let config = evm_config();
+		let mut tx = base_input();
+		tx.sender_code = Some(sp_std::vec![0x60, 0x00]);
+		assert_eq!(
+			check(&config, tx).validate_common().unwrap_err(),
+			InvalidEvmTransactionError::SenderHasDeployedCode
+		);
+	}
+
+	#[test]
+	fn allows_eip7702_delegation_designator() {
+		let config = evm_config();
+		let mut code = sp_std::vec![0xef, 0x01, 0x00];
+		code.extend_from_slice(&[0xaa; 20]);
+		let mut tx = base_input();
+		tx.sender_code = Some(code);
+		assert!(check(&config, tx).validate_common().is_ok());
+	}
+
+	#[test]
+	fn rejects_delegation_designator_near_misses() {
+		let config = evm_config();
+
+		// Wrong prefix, correct length.
+		let mut wrong_prefix = sp_std::vec![0xef, 0x01, 0x01];
+		wrong_prefix.extend_from_slice(&[0xaa; 20]);
+		let mut tx = base_input();
+		tx.sender_code = Some(wrong_prefix);
+		assert_eq!(
+			check(&config, tx).validate_common().unwrap_err(),
+			InvalidEvmTransactionError::SenderHasDeployedCode
+		);
+
+		// Correct prefix, wrong length.
+		let mut tx = base_input();
+		tx.sender_code = Some(sp_std::vec![0xef, 0x01, 0x00]);
+		assert_eq!(
+			check(&config, tx).validate_common().unwrap_err(),
+			InvalidEvmTransactionError::SenderHasDeployedCode
+		);
+	}
+
+	fn tx_with_cost(gas_price: u64, gas_limit: u64, value: u64, fee_payer: Option<H160>) -> CheckEvmTransactionInput {
+		let mut tx = base_input();
+		tx.gas_price = Some(U256::from(gas_price));
+		tx.gas_limit = U256::from(gas_limit);
+		tx.value = U256::from(value);
+		tx.fee_payer = fee_payer;
+		tx
+	}
+
+	#[test]
+	fn origin_pays_when_no_fee_payer() {
+		let config = evm_config();
+		let tx = tx_with_cost(1, 100, 0, None);
+		let check = check(&config, tx);
+		assert!(check.clone().with_balance_for(&U256::from(100u64), None).is_ok());
+		assert_eq!(
+			check.with_balance_for(&U256::from(99u64), None).unwrap_err(),
+			InvalidEvmTransactionError::BalanceTooLow
+		);
+	}
+
+	#[test]
+	fn fee_payer_balance_is_checked_when_set() {
+		let config = evm_config();
+		let payer = H160::from_low_u64_be(42);
+		let tx = tx_with_cost(1, 100, 0, Some(payer));
+		let check = check(&config, tx);
+		assert!(check
+			.clone()
+			.with_balance_for(&U256::zero(), Some(&U256::from(100u64)))
+			.is_ok());
+		assert_eq!(
+			check
+				.with_balance_for(&U256::zero(), Some(&U256::from(99u64)))
+				.unwrap_err(),
+			InvalidEvmTransactionError::FeePayerBalanceTooLow
+		);
+	}
+
+	#[test]
+	fn missing_fee_payer_balance_is_rejected_even_for_zero_cost() {
+		let config = evm_config();
+		let payer = H160::from_low_u64_be(42);
+		// Zero-cost transaction: gas_price 0, value 0. The missing-balance safeguard must still
+		// reject rather than comparing against a total_payment of zero.
+		let tx = tx_with_cost(0, 100, 0, Some(payer));
+		let check = check(&config, tx);
+		assert_eq!(
+			check
+				.with_balance_for(&U256::from(1_000_000u64), None)
+				.unwrap_err(),
+			InvalidEvmTransactionError::FeePayerBalanceTooLow
+		);
+	}
+
+	#[test]
+	fn rejects_max_fee_below_base_fee() {
+		let config = evm_config();
+		let mut tx = base_input();
+		tx.gas_price = None;
+		tx.max_fee_per_gas = Some(U256::from(5u64));
+		assert_eq!(
+			check_with_base_fee(&config, tx, U256::from(10u64))
+				.validate_common()
+				.unwrap_err(),
+			InvalidEvmTransactionError::GasPriceLessThanBaseFee
+		);
+	}
+
+	#[test]
+	fn rejects_priority_fee_above_max_fee() {
+		let config = evm_config();
+		let mut tx = base_input();
+		tx.gas_price = None;
+		tx.max_fee_per_gas = Some(U256::from(10u64));
+		tx.max_priority_fee_per_gas = Some(U256::from(20u64));
+		assert_eq!(
+			check(&config, tx).validate_common().unwrap_err(),
+			InvalidEvmTransactionError::PriorityFeeTooHigh
+		);
+	}
+
+	#[test]
+	fn accepts_valid_1559_fee_caps() {
+		let config = evm_config();
+		let mut tx = base_input();
+		tx.gas_price = None;
+		tx.max_fee_per_gas = Some(U256::from(20u64));
+		tx.max_priority_fee_per_gas = Some(U256::from(5u64));
+		assert!(check_with_base_fee(&config, tx, U256::from(10u64))
+			.validate_common()
+			.is_ok());
+	}
+}